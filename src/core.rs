@@ -1,16 +1,20 @@
 // main.rs
-use btleplug::api::{Central, Manager as _, Peripheral as _};
-use btleplug::platform::{Adapter, Manager};
+use btleplug::api::{Central, CentralEvent, Manager as _, Peripheral as _, PeripheralId, ScanFilter};
+use btleplug::platform::{Adapter, Manager, Peripheral};
 use futures::stream::StreamExt;
-use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use tokio::time;
 use uuid::Uuid;
 
 // Constants
 const UUID_STR: &str = "00002a37-0000-1000-8000-00805f9b34fb";
-const SUPPORT_DEVICES: &[&str] = &["Xiaomi Smart Band 9 082F"];
+const HEART_RATE_SERVICE_UUID_STR: &str = "0000180d-0000-1000-8000-00805f9b34fb";
+const BATTERY_LEVEL_UUID_STR: &str = "00002a19-0000-1000-8000-00805f9b34fb";
+const BODY_SENSOR_LOCATION_UUID_STR: &str = "00002a38-0000-1000-8000-00805f9b34fb";
+const DEFAULT_SCAN_TIMEOUT: Duration = Duration::from_secs(20);
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
 
 // Custom Error Type
 #[derive(thiserror::Error, Debug)]
@@ -21,10 +25,76 @@ pub enum Error {
     DeviceNotFound,
     #[error("Heart rate characteristic not found")]
     CharacteristicNotFound,
-    #[error("HeartRateMonitor not found")]
-    HeartRateMonitorNotFound,
     #[error("Bluetooth error: {0}")]
     Bluetooth(#[from] btleplug::Error),
+    #[error("Bluetooth adapter not found: {0}")]
+    AdapterNotFound(String),
+}
+
+// Decoded Heart Rate Measurement characteristic (0x2A37).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeartRateMeasurement {
+    pub bpm: u16,
+    pub sensor_contact: Option<bool>,
+    pub energy_expended: Option<u16>,
+    pub rr_intervals: Vec<u16>,
+}
+
+// A nearby device discovered by `scan`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanResult {
+    pub address: String,
+    pub local_name: Option<String>,
+    pub rssi: i16,
+}
+
+// Body Sensor Location characteristic (0x2A38) values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodySensorLocation {
+    Other,
+    Chest,
+    Wrist,
+    Finger,
+    Hand,
+    EarLobe,
+    Foot,
+    Unknown(u8),
+}
+
+impl BodySensorLocation {
+    fn from_byte(byte: u8) -> Self {
+        match byte {
+            0 => Self::Other,
+            1 => Self::Chest,
+            2 => Self::Wrist,
+            3 => Self::Finger,
+            4 => Self::Hand,
+            5 => Self::EarLobe,
+            6 => Self::Foot,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+// Battery level and Body Sensor Location, read alongside heart rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeviceInfo {
+    pub battery_level: Option<u8>,
+    pub body_sensor_location: Option<BodySensorLocation>,
+}
+
+// Link status for a monitoring session.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Connected,
+    Reconnecting,
+    Disconnected,
+}
+
+// Distinguishes a disconnect (reconnect) from the receiver being dropped (stop for good).
+enum MonitoringOutcome {
+    Disconnected,
+    ReceiverClosed,
 }
 
 // Heart Rate Monitor Struct
@@ -34,132 +104,499 @@ pub struct HeartRateMonitor {
 }
 
 impl HeartRateMonitor {
-    pub async fn new(adapter: Adapter, device_address: String) -> Self {
+    pub async fn new(adapter: Adapter, scan_result: ScanResult) -> Self {
         Self {
             adapter,
-            device_address,
+            device_address: scan_result.address,
         }
     }
 
-    pub async fn start_monitoring(&self) -> mpsc::Receiver<u8> {
+    pub async fn start_monitoring(
+        &self,
+    ) -> (
+        mpsc::Receiver<HeartRateMeasurement>,
+        watch::Receiver<ConnectionState>,
+        watch::Receiver<DeviceInfo>,
+    ) {
         let (sender, receiver) = mpsc::channel(100);
+        let (state_sender, state_receiver) = watch::channel(ConnectionState::Disconnected);
+        let (device_info_sender, device_info_receiver) = watch::channel(DeviceInfo::default());
         let adapter = self.adapter.clone();
         let device_address = self.device_address.clone();
-        let adapter_arc = Arc::new(adapter);
-        tokio::spawn({
-            let adapter_arc = Arc::clone(&adapter_arc);
-            async move {
-                loop {
-                    match Self::connect_and_monitor(&adapter_arc, &device_address, &sender).await {
-                        Ok(_) => break,
-                        Err(e) => {
-                            eprintln!("Monitoring error: {e}");
-                            time::sleep(Duration::from_secs(5)).await;
-                        }
+
+        tokio::spawn(async move {
+            let mut backoff = INITIAL_RECONNECT_DELAY;
+            let mut known_id: Option<PeripheralId> = None;
+
+            loop {
+                match Self::connect_and_monitor(
+                    &adapter,
+                    &device_address,
+                    &mut known_id,
+                    &sender,
+                    &state_sender,
+                    &device_info_sender,
+                )
+                .await
+                {
+                    Ok(MonitoringOutcome::ReceiverClosed) => break,
+                    Ok(MonitoringOutcome::Disconnected) => {
+                        let _ = state_sender.send(ConnectionState::Reconnecting);
+                    }
+                    Err(e) => {
+                        eprintln!("Monitoring error: {e}");
+                        let _ = state_sender.send(ConnectionState::Reconnecting);
                     }
                 }
+
+                time::sleep(backoff).await;
+                backoff = (backoff * 2).min(MAX_RECONNECT_DELAY);
             }
+
+            let _ = state_sender.send(ConnectionState::Disconnected);
         });
 
-        receiver
+        (receiver, state_receiver, device_info_receiver)
     }
 
     async fn connect_and_monitor(
         adapter: &Adapter,
         device_address: &str,
-        sender: &mpsc::Sender<u8>,
-    ) -> Result<(), Error> {
+        known_id: &mut Option<PeripheralId>,
+        sender: &mpsc::Sender<HeartRateMeasurement>,
+        state_sender: &watch::Sender<ConnectionState>,
+        device_info_sender: &watch::Sender<DeviceInfo>,
+    ) -> Result<MonitoringOutcome, Error> {
         let heart_rate_uuid: Uuid = Uuid::parse_str(UUID_STR).expect("UUID_STR");
-        adapter.start_scan(Default::default()).await?;
-        time::sleep(Duration::from_secs(5)).await;
+        let battery_level_uuid: Uuid = Uuid::parse_str(BATTERY_LEVEL_UUID_STR).expect("BATTERY_LEVEL_UUID_STR");
+        let body_sensor_location_uuid: Uuid =
+            Uuid::parse_str(BODY_SENSOR_LOCATION_UUID_STR).expect("BODY_SENSOR_LOCATION_UUID_STR");
 
-        let peripherals = adapter.peripherals().await?;
-        let device = peripherals
-            .into_iter()
-            .find(|p| p.address().to_string() == device_address)
-            .ok_or(Error::DeviceNotFound)?;
+        let mut events = adapter.events().await?;
+        let device = acquire_peripheral(
+            adapter,
+            &mut events,
+            device_address,
+            known_id.as_ref(),
+            DEFAULT_SCAN_TIMEOUT,
+        )
+        .await?;
+        *known_id = Some(device.id());
 
         device.connect().await?;
         device.discover_services().await?;
 
         let characteristics = device.characteristics();
         let hr_char = characteristics
-            .into_iter()
+            .iter()
             .find(|c| c.uuid == heart_rate_uuid)
+            .cloned()
             .ok_or(Error::CharacteristicNotFound)?;
+        let battery_char = characteristics
+            .iter()
+            .find(|c| c.uuid == battery_level_uuid)
+            .cloned();
+        let body_sensor_char = characteristics
+            .iter()
+            .find(|c| c.uuid == body_sensor_location_uuid)
+            .cloned();
+
+        let mut body_sensor_location = None;
+        if let Some(char_) = &body_sensor_char {
+            if let Ok(data) = device.read(char_).await {
+                body_sensor_location = data.first().copied().map(BodySensorLocation::from_byte);
+            }
+        }
+
+        let mut battery_level = None;
+        if let Some(char_) = &battery_char {
+            if let Ok(data) = device.read(char_).await {
+                battery_level = data.first().copied();
+            }
+        }
+
+        let _ = device_info_sender.send(DeviceInfo {
+            battery_level,
+            body_sensor_location,
+        });
 
         device.subscribe(&hr_char).await?;
+        if let Some(char_) = &battery_char {
+            let _ = device.subscribe(char_).await;
+        }
+
         let mut notification_stream = device.notifications().await?;
+        let device_id = device.id();
+
+        let _ = state_sender.send(ConnectionState::Connected);
 
-        while let Some(data) = notification_stream.next().await {
-            if data.uuid == heart_rate_uuid {
-                let value = parse_heart_rate(&data.value);
-                if sender.send(value).await.is_err() {
-                    break; // Receiver closed
+        let outcome = loop {
+            tokio::select! {
+                data = notification_stream.next() => {
+                    let Some(data) = data else { break MonitoringOutcome::Disconnected };
+                    if data.uuid == heart_rate_uuid {
+                        let value = parse_heart_rate(&data.value);
+                        if sender.send(value).await.is_err() {
+                            break MonitoringOutcome::ReceiverClosed;
+                        }
+                    } else if data.uuid == battery_level_uuid {
+                        battery_level = data.value.first().copied();
+                        let _ = device_info_sender.send(DeviceInfo {
+                            battery_level,
+                            body_sensor_location,
+                        });
+                    }
+                }
+                event = events.next() => {
+                    if let Some(CentralEvent::DeviceDisconnected(id)) = event {
+                        if id == device_id {
+                            break MonitoringOutcome::Disconnected;
+                        }
+                    }
                 }
             }
+        };
+
+        let _ = device.disconnect().await;
+        Ok(outcome)
+    }
+}
+
+// Re-acquires a peripheral by known id, falling back to a fresh scan.
+async fn acquire_peripheral(
+    adapter: &Adapter,
+    events: &mut (impl futures::Stream<Item = CentralEvent> + Unpin),
+    device_address: &str,
+    known_id: Option<&PeripheralId>,
+    timeout: Duration,
+) -> Result<Peripheral, Error> {
+    if let Some(id) = known_id {
+        if let Ok(peripheral) = adapter.peripheral(id).await {
+            return Ok(peripheral);
+        }
+    }
+
+    adapter.start_scan(Default::default()).await?;
+    let peripheral = wait_for_peripheral_by_address(adapter, events, device_address, timeout).await?;
+    adapter.stop_scan().await?;
+    Ok(peripheral)
+}
+
+async fn wait_for_peripheral_by_address(
+    adapter: &Adapter,
+    events: &mut (impl futures::Stream<Item = CentralEvent> + Unpin),
+    device_address: &str,
+    timeout: Duration,
+) -> Result<Peripheral, Error> {
+    let deadline = time::Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(time::Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::DeviceNotFound);
         }
 
-        device.disconnect().await?;
-        Ok(())
+        let event = tokio::select! {
+            event = events.next() => event,
+            _ = time::sleep(remaining) => None,
+        };
+
+        let Some(event) = event else {
+            return Err(Error::DeviceNotFound);
+        };
+
+        let id = match event {
+            CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) => id,
+            _ => continue,
+        };
+
+        let peripheral = adapter.peripheral(&id).await?;
+        if peripheral.address().to_string() == device_address {
+            return Ok(peripheral);
+        }
     }
 }
 
-fn parse_heart_rate(data: &[u8]) -> u8 {
-    if data.len() >= 2 {
-        if data[0] & 0x01 == 0 {
-            data[1]
+// Waits for a discovery event matching the Heart Rate Service (and `device_name`, if given).
+async fn wait_for_heart_rate_peripheral(
+    adapter: &Adapter,
+    events: &mut (impl futures::Stream<Item = CentralEvent> + Unpin),
+    heart_rate_service_uuid: Uuid,
+    device_name: Option<&str>,
+    timeout: Duration,
+) -> Result<Peripheral, Error> {
+    let deadline = time::Instant::now() + timeout;
+
+    loop {
+        let remaining = deadline.saturating_duration_since(time::Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::DeviceNotFound);
+        }
+
+        let event = tokio::select! {
+            event = events.next() => event,
+            _ = time::sleep(remaining) => None,
+        };
+
+        let Some(event) = event else {
+            return Err(Error::DeviceNotFound);
+        };
+
+        let id = match event {
+            CentralEvent::DeviceDiscovered(id) | CentralEvent::DeviceUpdated(id) => id,
+            _ => continue,
+        };
+
+        let peripheral = adapter.peripheral(&id).await?;
+
+        if let Some(name_filter) = device_name {
+            let matches_name = peripheral
+                .properties()
+                .await?
+                .and_then(|props| props.local_name)
+                .is_some_and(|name| name == name_filter);
+            if !matches_name {
+                continue;
+            }
+        }
+
+        if offers_heart_rate_service(&peripheral, heart_rate_service_uuid).await? {
+            return Ok(peripheral);
+        }
+    }
+}
+
+fn parse_heart_rate(data: &[u8]) -> HeartRateMeasurement {
+    let empty = HeartRateMeasurement {
+        bpm: 0,
+        sensor_contact: None,
+        energy_expended: None,
+        rr_intervals: Vec::new(),
+    };
+
+    if data.is_empty() {
+        return empty;
+    }
+
+    let flags = data[0];
+    let hr_value_format_16bit = flags & 0x01 != 0;
+    let sensor_contact_detected = flags & 0x02 != 0;
+    let sensor_contact_supported = flags & 0x04 != 0;
+    let energy_expended_present = flags & 0x08 != 0;
+    let rr_interval_present = flags & 0x10 != 0;
+
+    let mut cursor = 1;
+    let hr_len = if hr_value_format_16bit { 2 } else { 1 };
+    if data.len() < cursor + hr_len {
+        return empty;
+    }
+
+    let bpm = if hr_value_format_16bit {
+        let value = u16::from_le_bytes([data[cursor], data[cursor + 1]]);
+        cursor += 2;
+        value
+    } else {
+        let value = data[cursor] as u16;
+        cursor += 1;
+        value
+    };
+
+    let sensor_contact = sensor_contact_supported.then_some(sensor_contact_detected);
+
+    let energy_expended = if energy_expended_present {
+        if data.len() < cursor + 2 {
+            None
         } else {
-            u16::from_le_bytes([data[1], data[2]]) as u8
+            let value = u16::from_le_bytes([data[cursor], data[cursor + 1]]);
+            cursor += 2;
+            Some(value)
         }
     } else {
-        0
+        None
+    };
+
+    let rr_intervals = if rr_interval_present {
+        data[cursor..]
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    HeartRateMeasurement {
+        bpm,
+        sensor_contact,
+        energy_expended,
+        rr_intervals,
     }
 }
 
-async fn get_device_address(device_name: &str) -> Result<String, Error> {
-    let manager = Manager::new().await?;
-    let adapters = manager.adapters().await?;
-    let adapter = adapters
-        .into_iter()
-        .next()
-        .ok_or(Error::BluetoothAdaptersNotFound)?;
+// Returns `true` if the peripheral advertises or implements the Heart Rate Service.
+async fn offers_heart_rate_service(
+    peripheral: &impl btleplug::api::Peripheral,
+    heart_rate_service_uuid: Uuid,
+) -> Result<bool, Error> {
+    if let Some(props) = peripheral.properties().await? {
+        if props.services.contains(&heart_rate_service_uuid) {
+            return Ok(true);
+        }
+    }
 
-    adapter.start_scan(Default::default()).await?;
-    time::sleep(Duration::from_secs(5)).await;
+    peripheral.discover_services().await?;
+    Ok(peripheral
+        .services()
+        .iter()
+        .any(|service| service.uuid == heart_rate_service_uuid))
+}
+
+// Finds a Heart Rate Service peripheral, optionally matching `device_name`.
+async fn get_device_address(adapter: &Adapter, device_name: Option<&str>) -> Result<ScanResult, Error> {
+    let heart_rate_service_uuid: Uuid =
+        Uuid::parse_str(HEART_RATE_SERVICE_UUID_STR).expect("HEART_RATE_SERVICE_UUID_STR");
+
+    let mut events = adapter.events().await?;
+    adapter
+        .start_scan(ScanFilter {
+            services: vec![heart_rate_service_uuid],
+        })
+        .await?;
+
+    let peripheral = wait_for_heart_rate_peripheral(
+        adapter,
+        &mut events,
+        heart_rate_service_uuid,
+        device_name,
+        DEFAULT_SCAN_TIMEOUT,
+    )
+    .await?;
+    adapter.stop_scan().await?;
+
+    to_scan_result(&peripheral).await
+}
+
+// Builds a `ScanResult` from a peripheral's advertised properties.
+async fn to_scan_result(peripheral: &Peripheral) -> Result<ScanResult, Error> {
+    let properties = peripheral.properties().await?;
+    let (local_name, rssi) = match properties {
+        Some(props) => (props.local_name, props.rssi.unwrap_or_default()),
+        None => (None, 0),
+    };
+    Ok(ScanResult {
+        address: peripheral.address().to_string(),
+        local_name,
+        rssi,
+    })
+}
+
+// Scans for nearby Heart Rate Service devices for `scan_time`.
+pub async fn scan(scan_time: Duration, adapter_name: Option<&str>) -> Result<Vec<ScanResult>, Error> {
+    let heart_rate_service_uuid: Uuid =
+        Uuid::parse_str(HEART_RATE_SERVICE_UUID_STR).expect("HEART_RATE_SERVICE_UUID_STR");
+
+    let adapter = select_adapter(adapter_name).await?;
+
+    adapter
+        .start_scan(ScanFilter {
+            services: vec![heart_rate_service_uuid],
+        })
+        .await?;
+    time::sleep(scan_time).await;
+    adapter.stop_scan().await?;
 
     let peripherals = adapter.peripherals().await?;
+    let mut results = Vec::with_capacity(peripherals.len());
     for peripheral in peripherals {
-        let properties = peripheral.properties().await?;
-        if let Some(props) = properties {
-            if let Some(name) = props.local_name {
-                if name == device_name {
-                    let address = props.address.to_string();
-                    adapter.stop_scan().await?;
-                    return Ok(address);
-                }
-            }
+        if offers_heart_rate_service(&peripheral, heart_rate_service_uuid).await? {
+            results.push(to_scan_result(&peripheral).await?);
         }
     }
 
-    Err(Error::DeviceNotFound)
+    Ok(results)
 }
 
-pub async fn detect_monitor() -> Result<HeartRateMonitor, Error> {
-    for device_name in SUPPORT_DEVICES {
-        match get_device_address(device_name).await {
-            Ok(device_address) => {
-                let manager = Manager::new().await?;
-                let adapters = manager.adapters().await?;
-                let adapter = adapters
-                    .into_iter()
-                    .next()
-                    .ok_or(Error::BluetoothAdaptersNotFound)?;
-                let monitor = HeartRateMonitor::new(adapter, device_address).await;
-                return Ok(monitor);
-            }
-            Err(e) => eprintln!("Device not found: {device_name} - Error: {e}"),
+// Finds a Bluetooth adapter whose info string contains `name`.
+pub async fn get_adapter_by_name(name: &str) -> Result<Adapter, Error> {
+    let manager = Manager::new().await?;
+    let adapters = manager.adapters().await?;
+
+    for adapter in adapters {
+        let info = adapter.adapter_info().await?;
+        if info.contains(name) {
+            return Ok(adapter);
         }
     }
-    Err(Error::HeartRateMonitorNotFound)
+
+    Err(Error::AdapterNotFound(name.to_string()))
+}
+
+async fn select_adapter(adapter_name: Option<&str>) -> Result<Adapter, Error> {
+    match adapter_name {
+        Some(name) => get_adapter_by_name(name).await,
+        None => {
+            let manager = Manager::new().await?;
+            let adapters = manager.adapters().await?;
+            adapters
+                .into_iter()
+                .next()
+                .ok_or(Error::BluetoothAdaptersNotFound)
+        }
+    }
+}
+
+// Detects a Heart Rate Service monitor, optionally pinned to `adapter_name`/`device_name`.
+pub async fn detect_monitor(
+    adapter_name: Option<&str>,
+    device_name: Option<&str>,
+) -> Result<HeartRateMonitor, Error> {
+    let adapter = select_adapter(adapter_name).await?;
+    let scan_result = get_device_address(&adapter, device_name).await?;
+    Ok(HeartRateMonitor::new(adapter, scan_result).await)
+}
+
+// Convenience wrapper around `detect_monitor` with no adapter/device pinned.
+pub async fn create_monitor() -> Result<HeartRateMonitor, Error> {
+    detect_monitor(None, None).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_heart_rate_truncated_buffer_does_not_panic() {
+        let measurement = parse_heart_rate(&[0x01]);
+        assert_eq!(measurement.bpm, 0);
+    }
+
+    #[test]
+    fn parse_heart_rate_16bit_format() {
+        let measurement = parse_heart_rate(&[0x01, 0x48, 0x00]);
+        assert_eq!(measurement.bpm, 0x0048);
+    }
+
+    #[test]
+    fn parse_heart_rate_sensor_contact_supported_and_detected() {
+        let measurement = parse_heart_rate(&[0x06, 0x48]);
+        assert_eq!(measurement.sensor_contact, Some(true));
+    }
+
+    #[test]
+    fn parse_heart_rate_sensor_contact_supported_and_not_detected() {
+        let measurement = parse_heart_rate(&[0x04, 0x48]);
+        assert_eq!(measurement.sensor_contact, Some(false));
+    }
+
+    #[test]
+    fn parse_heart_rate_sensor_contact_not_supported() {
+        let measurement = parse_heart_rate(&[0x00, 0x48]);
+        assert_eq!(measurement.sensor_contact, None);
+    }
+
+    #[test]
+    fn parse_heart_rate_with_rr_intervals() {
+        let measurement = parse_heart_rate(&[0x10, 0x48, 0xe8, 0x03, 0xd0, 0x02]);
+        assert_eq!(measurement.bpm, 0x48);
+        assert_eq!(measurement.rr_intervals, vec![0x03e8, 0x02d0]);
+    }
 }
@@ -5,8 +5,8 @@ async fn main() {
     let Ok(monitor) = create_monitor().await else {
         return;
     };
-    let mut receiver = monitor.start_monitoring().await;
-    while let Some(hr) = receiver.recv().await {
-        println!("{hr}");
+    let (mut receiver, _state_receiver, _device_info_receiver) = monitor.start_monitoring().await;
+    while let Some(measurement) = receiver.recv().await {
+        println!("{}", measurement.bpm);
     }
 }